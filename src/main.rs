@@ -7,6 +7,10 @@ use std::sync::{Arc, Mutex};
 use clap::Parser;
 use memmap2::MmapOptions;
 
+mod tokens;
+
+use tokens::TokenSet;
+
 #[derive(Clone)]
 struct Stats {
     byte_count: [usize; 256],
@@ -24,167 +28,6 @@ impl Stats {
     }
 }
 
-#[derive(Clone, Debug)]
-struct Token {
-    string: Vec<u8>,
-    is_literal: bool,
-    is_mandatory: bool,
-    // Index of the longest other token which is a suffix of this one.
-    suffix: Option<usize>,
-    cost: usize,
-}
-
-impl Token {
-    fn new(string: &[u8], is_literal: bool, is_mandatory: bool, cost: usize) -> Self {
-        Token {
-            string: string.to_vec(),
-            is_literal,
-            is_mandatory,
-            suffix: None,
-            cost,
-        }
-    }
-}
-
-#[derive(Clone)]
-struct TokenSet {
-    tokens: Vec<Token>,
-    tokens_by_string: HashMap<Vec<u8>, usize>,
-    literal_cost: usize,
-    ntokens: usize,
-}
-
-impl TokenSet {
-    fn add_mandatory_token(&mut self, string: &[u8]) {
-        if let Some(&existing) = self.tokens_by_string.get(string) {
-            let existing = &self.tokens[existing];
-            assert!(existing.is_literal);
-        }
-        let index = self.tokens.len();
-        let token = Token::new(string, false, true, 1);
-        self.tokens_by_string.insert(token.string.clone(), index);
-        self.tokens.push(token);
-        self.ntokens += 1;
-    }
-
-    fn add_token(&mut self, string: &[u8]) {
-        if let Some(&existing) = self.tokens_by_string.get(string) {
-            let existing = &self.tokens[existing];
-            if !existing.is_literal {
-                return;
-            }
-        }
-
-        let index = self.tokens.len();
-        let token = Token::new(string, false, false, 1);
-        self.tokens_by_string.insert(token.string.clone(), index);
-        self.tokens.push(token);
-        self.ntokens += 1;
-    }
-
-    fn add_literal(&mut self, value: u8) {
-        let token = Token::new(&[value], true, false, self.literal_cost);
-        self.tokens_by_string
-            .insert(token.string.clone(), self.tokens.len());
-        self.tokens.push(token);
-        self.ntokens += 1;
-    }
-
-    fn remove_token(&mut self, token_id: usize) {
-        assert!(token_id >= 256); // Can't remove literals
-        self.tokens.remove(token_id);
-        self.ntokens -= 1;
-
-        self.tokens_by_string.clear();
-        for i in 0..self.ntokens {
-            let token = &self.tokens[i];
-            self.tokens_by_string.insert(token.string.clone(), i);
-        }
-    }
-
-    fn build_with_hex_literals() -> Self {
-        let mut token_set = TokenSet {
-            tokens: Vec::new(),
-            tokens_by_string: HashMap::new(),
-            literal_cost: 3,
-            ntokens: 0,
-        };
-
-        for i in 0..=255 {
-            token_set.add_literal(i);
-        }
-        token_set.add_mandatory_token(&[0x10]);
-        for i in ('0' as u8)..=('9' as u8) {
-            token_set.add_mandatory_token(&[i]);
-        }
-        for i in ('a' as u8)..=('f' as u8) {
-            token_set.add_mandatory_token(&[i]);
-        }
-
-        token_set.ntokens = token_set.tokens.len();
-
-        token_set
-    }
-
-    fn build_with_bin_literals() -> Self {
-        let mut token_set = TokenSet {
-            tokens: Vec::new(),
-            tokens_by_string: HashMap::new(),
-            literal_cost: 8,
-            ntokens: 0,
-        };
-
-        for i in 0..=255 {
-            token_set.add_literal(i);
-        }
-        token_set.add_mandatory_token(&[0x11]);
-        token_set.add_mandatory_token(&[0x12]);
-
-        token_set.ntokens = token_set.tokens.len();
-
-        token_set
-    }
-
-    fn generate_suffixes(&mut self) {
-        for i in 256..self.tokens.len() {
-            let mut token = &mut self.tokens[i];
-            for start in 1..token.string.len() {
-                if let Some(&idx) = self.tokens_by_string.get(&token.string[start..]) {
-                    token.suffix = Some(idx);
-                    break;
-                }
-            }
-        }
-    }
-
-    fn to_json(&self) -> json::JsonValue {
-        let mut out = json::object! {
-            tokens: []
-        };
-
-        let mut token_strs = vec![];
-
-        for token in self.tokens.iter() {
-            if !token.is_literal {
-                token_strs.push(token.string.clone());
-            }
-        }
-
-        token_strs.sort_unstable();
-
-        for token_str in token_strs.iter() {
-            let value: json::JsonValue = match std::str::from_utf8(&token_str) {
-                Ok(s) => s.into(),
-                Err(_) => token_str.as_slice().into(),
-            };
-
-            out["tokens"].push(value).unwrap();
-        }
-
-        out
-    }
-}
-
 #[derive(Debug)]
 struct SuffixState {
     suffix: Vec<u8>,
@@ -383,7 +226,7 @@ impl Tokenizer {
             let token = &self.token_set.tokens[token_id];
             token_stats.token_count[token_id] += 1;
 
-            token_stats.pair_count[token_id * self.token_set.ntokens + next_token_id] += 1;
+            token_stats.pair_count[token_id * self.token_set.ntokens() + next_token_id] += 1;
 
             next_token_id = token_id;
             pos = pos.checked_sub(token.string.len()).unwrap();
@@ -528,8 +371,8 @@ fn optimize_bpe(token_set: &TokenSet, ntokens: usize, filename: &str) -> (TokenS
             }
         }
 
-        let ifirst = top_pair / token_set.ntokens;
-        let isecond = top_pair % token_set.ntokens;
+        let ifirst = top_pair / token_set.ntokens();
+        let isecond = top_pair % token_set.ntokens();
 
         let mut token_str = token_set.tokens[ifirst].string.clone();
         token_str.extend(token_set.tokens[isecond].string.clone());
@@ -556,7 +399,7 @@ fn optimize_bpe(token_set: &TokenSet, ntokens: usize, filename: &str) -> (TokenS
             format_token(&new_token_str)
         );
 
-        if new_token_set.ntokens > 256 + ntokens {
+        if new_token_set.ntokens() > 256 + ntokens {
             let stats = tokenize_file(&new_token_set, filename);
             let mut token_ids: Vec<usize> = (0..new_token_set.tokens.len()).collect();
             token_ids.sort_unstable_by_key(|&i| stats.token_count[i]);
@@ -572,7 +415,7 @@ fn optimize_bpe(token_set: &TokenSet, ntokens: usize, filename: &str) -> (TokenS
                 tries += 1;
                 let token_str = token_to_remove.string.clone();
 
-                new_token_set.remove_token(token_id_to_remove);
+                new_token_set.remove_token(&token_str);
 
                 let stats = tokenize_file(&new_token_set, filename);
 
@@ -615,40 +458,70 @@ struct Args {
 
     #[arg(short, long, default_value_t = 0)]
     ntokens: usize,
-}
 
-fn main() {
-    let args = Args::parse();
+    #[arg(long, default_value_t = String::new())]
+    dict_in: String,
 
-    let mut fallback16 = false;
+    #[arg(long, default_value_t = String::new())]
+    dict_out: String,
 
-    let token_set = if args.input.is_empty() {
-        TokenSet::build_with_bin_literals()
+    #[arg(long, default_value_t = 0)]
+    train_bpe: usize,
+
+    #[arg(long, default_value_t = 0)]
+    prune_to: usize,
+}
+
+fn run_tokens_pipeline(args: &Args) {
+    let mut token_set = if !args.dict_in.is_empty() {
+        TokenSet::from_dict_file(&args.dict_in)
     } else {
-        let contents = std::fs::read_to_string(args.input).unwrap();
-        let parsed = json::parse(&contents).unwrap();
+        TokenSet::build_with_bin_literals()
+    };
 
-        fallback16 = parsed["config"]["fallback16"].as_bool().unwrap();
+    if args.train_bpe > 0 {
+        let corpus = std::fs::read(&args.data).unwrap();
+        token_set.train_bpe(&corpus, token_set.ntokens() + args.train_bpe, 0);
 
-        let mut token_set = if fallback16 {
-            TokenSet::build_with_hex_literals()
-        } else {
-            TokenSet::build_with_bin_literals()
-        };
+        let automaton = token_set.build_automaton();
+        let mut counts: HashMap<Vec<u8>, u64> = HashMap::new();
+        for (_, token_id) in automaton.matches(&corpus) {
+            *counts
+                .entry(token_set.tokens[token_id].string.clone())
+                .or_insert(0) += 1;
+        }
+        token_set.assign_costs_from_frequencies(&counts);
 
-        for token_str in parsed["tokens"].members() {
-            if token_str.is_string() {
-                token_set.add_token(token_str.as_str().unwrap().as_bytes());
-            } else {
-                let mut s = vec![];
-                for b in token_str.members() {
-                    s.push(b.as_u8().unwrap());
-                }
-                token_set.add_token(&s);
-            }
+        if args.prune_to > 0 {
+            token_set.prune_to(256 + args.prune_to, &corpus);
         }
+    }
+
+    if !args.dict_out.is_empty() {
+        token_set.to_dict_file(&args.dict_out);
+        return;
+    }
 
-        token_set
+    let tokens_json_str = json::stringify_pretty(token_set.to_json(), 2);
+    println!("{}", &tokens_json_str);
+
+    if !args.output.is_empty() {
+        std::fs::write(&args.output, &tokens_json_str).unwrap();
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    if !args.dict_in.is_empty() || !args.dict_out.is_empty() || args.train_bpe > 0 {
+        run_tokens_pipeline(&args);
+        return;
+    }
+
+    let token_set = if args.input.is_empty() {
+        TokenSet::build_with_bin_literals()
+    } else {
+        TokenSet::from_json(&args.input)
     };
 
     let tokens_json = token_set.to_json();
@@ -660,12 +533,11 @@ fn main() {
 
     let mut tokens_json = token_set.to_json();
 
-    tokens_json["stats"]["ntokens"] = (token_set.ntokens - 256).into();
+    tokens_json["stats"]["ntokens"] = (token_set.ntokens() - 256).into();
     tokens_json["stats"]["scanned_bytes"] = token_stats.scanned_bytes.into();
     tokens_json["stats"]["total_tokens"] = token_stats.cost.into();
     tokens_json["stats"]["bytes_per_token"] =
         (token_stats.scanned_bytes as f64 / token_stats.cost as f64).into();
-    tokens_json["config"]["fallback16"] = fallback16.into();
 
     let tokens_json_str = json::stringify_pretty(tokens_json, 2);
     println!("{}", &tokens_json_str);
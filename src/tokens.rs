@@ -1,5 +1,126 @@
 use std::collections::HashMap;
 
+fn decode_dict_value(quoted: &str) -> Vec<u8> {
+    let bytes = quoted.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && i + 1 < bytes.len() {
+            match bytes[i + 1] {
+                b'\\' => {
+                    out.push(b'\\');
+                    i += 2;
+                }
+                b'"' => {
+                    out.push(b'"');
+                    i += 2;
+                }
+                b'x' => {
+                    let hex = bytes
+                        .get(i + 2..i + 4)
+                        .and_then(|h| std::str::from_utf8(h).ok())
+                        .and_then(|h| u8::from_str_radix(h, 16).ok());
+                    match hex {
+                        Some(byte) => {
+                            out.push(byte);
+                            i += 4;
+                        }
+                        None => {
+                            // Truncated or non-hex `\x` escape: keep it
+                            // verbatim instead of aborting the whole decode.
+                            out.push(bytes[i]);
+                            i += 1;
+                        }
+                    }
+                }
+                _ => {
+                    out.push(bytes[i]);
+                    i += 1;
+                }
+            }
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    out
+}
+
+fn encode_dict_value(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+
+    for &byte in bytes {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+
+    out
+}
+
+// Extracts the quoted value from a dictionary line such as
+// `name="value"` or a bare `"value"`, tolerating trailing content (e.g. a
+// comment) after the closing quote. Returns `None` if the line has no
+// quote or the quote is never closed.
+fn extract_quoted_value(line: &str) -> Option<&str> {
+    let quote_pos = line.find('"')?;
+    let rest = &line[quote_pos + 1..];
+
+    let mut escaped = false;
+    for (i, byte) in rest.bytes().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match byte {
+            b'\\' => escaped = true,
+            b'"' => return Some(&rest[..i]),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn decode_token_value(value: &json::JsonValue) -> Vec<u8> {
+    if value.is_string() {
+        value.as_str().unwrap().as_bytes().to_vec()
+    } else {
+        let mut s = vec![];
+        for b in value.members() {
+            s.push(b.as_u8().unwrap());
+        }
+        s
+    }
+}
+
+// Fixed-point unit for quantized per-token costs: 1000 millibits per bit.
+const MILLIBITS_PER_BIT: f64 = 1000.0;
+
+fn quantize_cost(p: f64) -> usize {
+    if p <= 0.0 {
+        return usize::MAX / 2;
+    }
+    ((-p.log2()) * MILLIBITS_PER_BIT).round().max(1.0) as usize
+}
+
+fn increment_pair(pair_count: &mut HashMap<(usize, usize), u32>, pair: (usize, usize)) {
+    *pair_count.entry(pair).or_insert(0) += 1;
+}
+
+fn decrement_pair(pair_count: &mut HashMap<(usize, usize), u32>, pair: (usize, usize)) {
+    if let Some(count) = pair_count.get_mut(&pair) {
+        *count -= 1;
+        if *count == 0 {
+            pair_count.remove(&pair);
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Token {
@@ -23,6 +144,46 @@ impl Token {
     }
 }
 
+// A node in the Aho-Corasick trie built over the non-literal token
+// strings, used while constructing the automaton and the suffix links.
+struct AcNode {
+    children: HashMap<u8, usize>,
+    fail: usize,
+    // The token whose string exactly matches the path from the root to
+    // this node, if any.
+    token_id: Option<usize>,
+}
+
+impl AcNode {
+    fn new() -> Self {
+        AcNode {
+            children: HashMap::new(),
+            fail: 0,
+            token_id: None,
+        }
+    }
+}
+
+pub struct AhoCorasick {
+    goto: Vec<[usize; 256]>,
+    outputs: Vec<Vec<usize>>,
+}
+
+impl AhoCorasick {
+    pub fn matches<'a>(&'a self, input: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        input
+            .iter()
+            .enumerate()
+            .scan(0usize, move |state, (pos, &byte)| {
+                *state = self.goto[*state][byte as usize];
+                Some((pos + 1, *state))
+            })
+            .flat_map(move |(end_pos, state)| {
+                self.outputs[state].iter().map(move |&token_id| (end_pos, token_id))
+            })
+    }
+}
+
 #[derive(Clone)]
 pub struct TokenSet {
     pub tokens: Vec<Token>,
@@ -48,16 +209,21 @@ impl TokenSet {
     }
 
     pub fn add_token(&mut self, string: &[u8]) {
+        self.add_token_with_cost(string, 1);
+    }
+
+    pub fn add_token_with_cost(&mut self, string: &[u8], cost: usize) {
         if let Some(&existing) = self.tokens_by_string.get(string) {
             let existing = &self.tokens[existing];
             assert!(existing.is_literal || existing.is_mandatory);
-            if !existing.is_literal {
-                return;
-            }
+            // A single-byte string already has a literal or mandatory
+            // token covering it; adding a non-literal duplicate would
+            // shadow it in `tokens_by_string` and orphan the original.
+            return;
         }
 
         let index = self.tokens.len();
-        let token = Token::new(string, false, false, 1);
+        let token = Token::new(string, false, false, cost);
         self.tokens_by_string.insert(token.string.clone(), index);
         self.tokens.push(token);
     }
@@ -70,16 +236,30 @@ impl TokenSet {
     }
 
     pub fn remove_token(&mut self, token_str: &[u8]) {
-        let token_id = *self.tokens_by_string.get(token_str).unwrap();
+        self.remove_tokens(std::slice::from_ref(&token_str.to_vec()));
+    }
+
+    pub fn remove_tokens(&mut self, token_strs: &[Vec<u8>]) {
+        let mut to_remove = std::collections::HashSet::new();
+        for token_str in token_strs.iter() {
+            let token_id = *self.tokens_by_string.get(token_str.as_slice()).unwrap();
+
+            assert!(token_id >= 256); // Can't remove literals
+            assert!(!self.tokens[token_id].is_literal);
+            assert!(!self.tokens[token_id].is_mandatory);
+            to_remove.insert(token_id);
+        }
 
-        assert!(token_id >= 256); // Can't remove literals
-        assert!(!self.tokens[token_id].is_literal);
-        assert!(!self.tokens[token_id].is_mandatory);
-        self.tokens.remove(token_id);
+        let mut kept = Vec::with_capacity(self.tokens.len() - to_remove.len());
+        for (token_id, token) in self.tokens.drain(..).enumerate() {
+            if !to_remove.contains(&token_id) {
+                kept.push(token);
+            }
+        }
+        self.tokens = kept;
 
         self.tokens_by_string.clear();
-        for i in 0..self.ntokens() {
-            let token = &self.tokens[i];
+        for (i, token) in self.tokens.iter().enumerate() {
             self.tokens_by_string.insert(token.string.clone(), i);
         }
     }
@@ -133,31 +313,344 @@ impl TokenSet {
             TokenSet::build_with_bin_literals()
         };
 
-        for token_str in parsed["tokens"].members() {
-            if token_str.is_string() {
-                token_set.add_token(token_str.as_str().unwrap().as_bytes());
+        for entry in parsed["tokens"].members() {
+            if entry.is_object() {
+                let bytes = decode_token_value(&entry["token"]);
+                let cost = entry["cost"]
+                    .as_usize()
+                    .or_else(|| entry["weight"].as_usize())
+                    .unwrap_or(1);
+                token_set.add_token_with_cost(&bytes, cost);
             } else {
-                let mut s = vec![];
-                for b in token_str.members() {
-                    s.push(b.as_u8().unwrap());
-                }
-                token_set.add_token(&s);
+                token_set.add_token(&decode_token_value(entry));
+            }
+        }
+
+        token_set
+    }
+
+    pub fn from_dict_file(filename: &str) -> Self {
+        let contents = std::fs::read_to_string(filename).unwrap();
+        let mut token_set = TokenSet::build_with_bin_literals();
+
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // "name=" and "name@level=" are optional; a bare quoted value
+            // is also valid AFL/libFuzzer dictionary syntax. Lines that
+            // don't fit this shape (no quoted value, or an unterminated
+            // quote) are skipped rather than aborting the whole load.
+            match extract_quoted_value(line) {
+                Some(quoted) => token_set.add_token(&decode_dict_value(quoted)),
+                None => continue,
             }
         }
 
         token_set
+    }
+
+    pub fn to_dict_file(&self, filename: &str) {
+        let mut out = String::new();
+
+        let mut index = 0;
+        for token in self.tokens.iter() {
+            if token.is_literal || token.is_mandatory {
+                continue;
+            }
+            out.push_str(&format!(
+                "token_{}=\"{}\"\n",
+                index,
+                encode_dict_value(&token.string)
+            ));
+            index += 1;
+        }
+
+        std::fs::write(filename, out).unwrap();
+    }
+
+    // Merges never cross a doc_boundary byte or merge away a mandatory token.
+    pub fn train_bpe(&mut self, corpus: &[u8], target_ntokens: usize, doc_boundary: u8) {
+        let mut sequence: Vec<usize> = corpus.iter().map(|&b| b as usize).collect();
+        let sentinel = doc_boundary as usize;
+
+        let mut pair_count: HashMap<(usize, usize), u32> = HashMap::new();
+        for window in sequence.windows(2) {
+            let (a, b) = (window[0], window[1]);
+            if a != sentinel && b != sentinel {
+                increment_pair(&mut pair_count, (a, b));
+            }
+        }
+
+        while self.ntokens() < target_ntokens {
+            let best_pair = pair_count
+                .iter()
+                .filter(|&(_, &count)| count > 1)
+                .max_by_key(|&(_, &count)| count)
+                .map(|(&pair, _)| pair);
+
+            let (a, b) = match best_pair {
+                Some(pair) => pair,
+                None => break,
+            };
+
+            let mut merged = self.tokens[a].string.clone();
+            merged.extend_from_slice(&self.tokens[b].string);
+
+            if self.tokens[a].is_mandatory
+                || self.tokens[b].is_mandatory
+                || self.tokens_by_string.contains_key(&merged)
+            {
+                pair_count.remove(&(a, b));
+                continue;
+            }
+
+            self.add_token(&merged);
+            let new_id = self.tokens_by_string[&merged];
+            pair_count.remove(&(a, b));
+
+            let mut new_sequence = Vec::with_capacity(sequence.len());
+            let mut i = 0;
+            while i < sequence.len() {
+                if i + 1 < sequence.len() && sequence[i] == a && sequence[i + 1] == b {
+                    match new_sequence.last() {
+                        Some(&prev) if prev != sentinel => {
+                            decrement_pair(&mut pair_count, (prev, a));
+                            increment_pair(&mut pair_count, (prev, new_id));
+                        }
+                        _ => {}
+                    }
+                    if i + 2 < sequence.len() && sequence[i + 2] != sentinel {
+                        let next = sequence[i + 2];
+                        decrement_pair(&mut pair_count, (b, next));
+                        increment_pair(&mut pair_count, (new_id, next));
+                    }
+                    new_sequence.push(new_id);
+                    i += 2;
+                } else {
+                    new_sequence.push(sequence[i]);
+                    i += 1;
+                }
+            }
+            sequence = new_sequence;
+        }
 
+        self.generate_suffixes();
     }
 
     pub fn generate_suffixes(&mut self) {
+        let (nodes, token_node, _order) = self.build_trie();
+
         for i in 256..self.tokens.len() {
-            let mut token = &mut self.tokens[i];
-            for start in 1..token.string.len() {
-                if let Some(&idx) = self.tokens_by_string.get(&token.string[start..]) {
-                    token.suffix = Some(idx);
-                    break;
+            let mut fail = nodes[token_node[i]].fail;
+            let trie_suffix = loop {
+                if let Some(id) = nodes[fail].token_id {
+                    break Some(id);
+                }
+                if fail == 0 {
+                    break None;
+                }
+                fail = nodes[fail].fail;
+            };
+            // Literals are not in the trie: fall back to the token for the
+            // last byte when no longer token is a proper suffix.
+            let string = &self.tokens[i].string;
+            let suffix = trie_suffix.or_else(|| {
+                if string.len() > 1 {
+                    self.tokens_by_string.get(&string[string.len() - 1..]).copied()
+                } else {
+                    None
+                }
+            });
+            self.tokens[i].suffix = suffix;
+        }
+    }
+
+    // Returns the trie nodes, a token id -> node index map, and the BFS
+    // order (a node's failure link always precedes it in this order).
+    fn build_trie(&self) -> (Vec<AcNode>, Vec<usize>, Vec<usize>) {
+        let mut nodes = vec![AcNode::new()];
+        let mut token_node = vec![0usize; self.tokens.len()];
+
+        for (token_id, token) in self.tokens.iter().enumerate() {
+            if token.is_literal {
+                continue;
+            }
+
+            let mut node = 0;
+            for &byte in token.string.iter() {
+                node = match nodes[node].children.get(&byte) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(AcNode::new());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.insert(byte, child);
+                        child
+                    }
+                };
+            }
+            nodes[node].token_id = Some(token_id);
+            token_node[token_id] = node;
+        }
+
+        let mut order = vec![0];
+        let mut queue: std::collections::VecDeque<usize> = std::collections::VecDeque::new();
+
+        let root_children: Vec<usize> = nodes[0].children.values().cloned().collect();
+        for child in root_children {
+            nodes[child].fail = 0;
+            order.push(child);
+            queue.push_back(child);
+        }
+
+        while let Some(node) = queue.pop_front() {
+            let children: Vec<(u8, usize)> =
+                nodes[node].children.iter().map(|(&b, &c)| (b, c)).collect();
+
+            for (byte, child) in children {
+                let mut fail = nodes[node].fail;
+                nodes[child].fail = loop {
+                    if let Some(&c) = nodes[fail].children.get(&byte) {
+                        break c;
+                    }
+                    if fail == 0 {
+                        break 0;
+                    }
+                    fail = nodes[fail].fail;
+                };
+                order.push(child);
+                queue.push_back(child);
+            }
+        }
+
+        (nodes, token_node, order)
+    }
+
+    pub fn build_automaton(&self) -> AhoCorasick {
+        let (nodes, _token_node, order) = self.build_trie();
+        let n = nodes.len();
+
+        let mut goto = vec![[0usize; 256]; n];
+        let mut outputs = vec![Vec::new(); n];
+
+        for &node in order.iter() {
+            let fail_row = goto[nodes[node].fail];
+            for (byte, slot) in goto[node].iter_mut().enumerate() {
+                *slot = match nodes[node].children.get(&(byte as u8)) {
+                    Some(&child) => child,
+                    None if node == 0 => 0,
+                    None => fail_row[byte],
+                };
+            }
+
+            outputs[node] = match nodes[node].token_id {
+                Some(id) if node == 0 => vec![id],
+                Some(id) => {
+                    let mut ids = vec![id];
+                    ids.extend_from_slice(&outputs[nodes[node].fail]);
+                    ids
                 }
+                None if node == 0 => Vec::new(),
+                None => outputs[nodes[node].fail].clone(),
+            };
+        }
+
+        AhoCorasick { goto, outputs }
+    }
+
+    fn min_cost_tokenization(&self, sample: &[u8]) -> (u64, Vec<u64>) {
+        let automaton = self.build_automaton();
+        let n = sample.len();
+
+        let mut best_cost = vec![u64::MAX; n + 1];
+        let mut best_token = vec![0usize; n + 1];
+        best_cost[0] = 0;
+
+        let mut state = 0usize;
+        for (pos, &byte) in sample.iter().enumerate() {
+            state = automaton.goto[state][byte as usize];
+
+            if best_cost[pos] != u64::MAX {
+                let literal_id = byte as usize;
+                let cost = best_cost[pos].saturating_add(self.tokens[literal_id].cost as u64);
+                if cost < best_cost[pos + 1] {
+                    best_cost[pos + 1] = cost;
+                    best_token[pos + 1] = literal_id;
+                }
+            }
+
+            for &token_id in automaton.outputs[state].iter() {
+                let token = &self.tokens[token_id];
+                let start = pos + 1 - token.string.len();
+                if best_cost[start] == u64::MAX {
+                    continue;
+                }
+                let cost = best_cost[start].saturating_add(token.cost as u64);
+                if cost < best_cost[pos + 1] {
+                    best_cost[pos + 1] = cost;
+                    best_token[pos + 1] = token_id;
+                }
+            }
+        }
+
+        let mut usage = vec![0u64; self.tokens.len()];
+        let mut pos = n;
+        while pos > 0 {
+            let token_id = best_token[pos];
+            usage[token_id] += 1;
+            pos -= self.tokens[token_id].string.len();
+        }
+
+        (best_cost[n], usage)
+    }
+
+    // Greedily drops the least valuable non-literal, non-mandatory tokens
+    // in batches until `target_ntokens` remain, or until no removable token
+    // is left.
+    pub fn prune_to(&mut self, target_ntokens: usize, sample: &[u8]) {
+        while self.ntokens() > target_ntokens {
+            let (_, usage) = self.min_cost_tokenization(sample);
+
+            let mut candidates: Vec<(usize, u64)> = self
+                .tokens
+                .iter()
+                .enumerate()
+                .filter(|(_, token)| !token.is_literal && !token.is_mandatory)
+                .map(|(token_id, token)| {
+                    let replacement_cost = match token.suffix {
+                        Some(suffix_id) => {
+                            let suffix = &self.tokens[suffix_id];
+                            (self.literal_cost as u64)
+                                .saturating_mul((token.string.len() - suffix.string.len()) as u64)
+                                .saturating_add(suffix.cost as u64)
+                        }
+                        None => (self.literal_cost as u64)
+                            .saturating_mul(token.string.len() as u64),
+                    };
+                    let increase = usage[token_id]
+                        .saturating_mul(replacement_cost.saturating_sub(token.cost as u64));
+                    (token_id, increase)
+                })
+                .collect();
+
+            if candidates.is_empty() {
+                break;
             }
+
+            candidates.sort_unstable_by_key(|&(_, increase)| increase);
+
+            let excess = self.ntokens() - target_ntokens;
+            let batch_size = excess.min(candidates.len()).min(candidates.len() / 10 + 1);
+
+            let to_remove: Vec<Vec<u8>> = candidates[..batch_size]
+                .iter()
+                .map(|&(token_id, _)| self.tokens[token_id].string.clone())
+                .collect();
+
+            self.remove_tokens(&to_remove);
+            self.generate_suffixes();
         }
     }
 
@@ -166,27 +659,242 @@ impl TokenSet {
             tokens: []
         };
 
-        let mut token_strs = vec![];
+        let mut token_entries = vec![];
 
         for token in self.tokens.iter() {
             if !token.is_literal {
-                token_strs.push(token.string.clone());
+                token_entries.push((token.string.clone(), token.cost));
             }
         }
 
-        token_strs.sort_unstable();
+        token_entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
 
-        for token_str in token_strs.iter() {
-            let value: json::JsonValue = match std::str::from_utf8(&token_str) {
+        for (token_str, cost) in token_entries.iter() {
+            let value: json::JsonValue = match std::str::from_utf8(token_str) {
                 Ok(s) => s.into(),
                 Err(_) => token_str.as_slice().into(),
             };
 
-            out["tokens"].push(value).unwrap();
+            let entry = if *cost == 1 {
+                value
+            } else {
+                json::object! {
+                    token: value,
+                    cost: *cost as u64,
+                }
+            };
+
+            out["tokens"].push(entry).unwrap();
         }
 
         out["config"]["fallback16"] = self.fallback16.into();
 
         out
     }
+
+    pub fn assign_costs_from_frequencies(&mut self, counts: &HashMap<Vec<u8>, u64>) {
+        let total: u64 = counts.values().sum();
+        if total == 0 {
+            return;
+        }
+
+        let mut matched = 0u64;
+        for token in self.tokens.iter_mut() {
+            if token.is_literal {
+                continue;
+            }
+            token.cost = match counts.get(&token.string) {
+                Some(&count) => {
+                    matched += count;
+                    quantize_cost(count as f64 / total as f64)
+                }
+                // Not observed in this corpus: cost it as if it were
+                // vanishingly rare, not whatever cost it happened to have
+                // before, so min_cost_tokenization doesn't treat it as free.
+                None => quantize_cost(0.0),
+            };
+        }
+
+        let residual = total.saturating_sub(matched);
+        self.literal_cost = quantize_cost(residual as f64 / total as f64 / 256.0);
+        for token in self.tokens[0..256].iter_mut() {
+            token.cost = self.literal_cost;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dict_value_round_trips_through_encode_decode() {
+        let bytes = b"GET /\x00\x7f\\\"".to_vec();
+        assert_eq!(decode_dict_value(&encode_dict_value(&bytes)), bytes);
+    }
+
+    #[test]
+    fn decode_dict_value_tolerates_truncated_hex_escape() {
+        assert_eq!(decode_dict_value("\\x4"), b"\\x4");
+        assert_eq!(decode_dict_value("ab\\x4"), b"ab\\x4");
+    }
+
+    #[test]
+    fn from_dict_file_accepts_named_and_bare_entries() {
+        std::fs::write(
+            "/tmp/tokens_dict_test.dict",
+            "# comment\nkw1=\"GET \"\nkw2@10=\"POST \"\n\"bare\"\n",
+        )
+        .unwrap();
+        let token_set = TokenSet::from_dict_file("/tmp/tokens_dict_test.dict");
+        std::fs::remove_file("/tmp/tokens_dict_test.dict").unwrap();
+
+        assert!(token_set.tokens_by_string.contains_key(b"GET ".as_slice()));
+        assert!(token_set.tokens_by_string.contains_key(b"POST ".as_slice()));
+        assert!(token_set.tokens_by_string.contains_key(b"bare".as_slice()));
+    }
+
+    #[test]
+    fn single_byte_dict_entries_do_not_shadow_literals() {
+        std::fs::write("/tmp/tokens_dict_single_byte_test.dict", "kw=\"A\"\n").unwrap();
+        let token_set = TokenSet::from_dict_file("/tmp/tokens_dict_single_byte_test.dict");
+        std::fs::remove_file("/tmp/tokens_dict_single_byte_test.dict").unwrap();
+
+        let literal_id = token_set.tokens_by_string[b"A".as_slice()];
+        assert!(token_set.tokens[literal_id].is_literal);
+        assert_eq!(
+            token_set.tokens.iter().filter(|t| t.string == b"A").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn from_dict_file_skips_malformed_lines_and_trailing_comments() {
+        std::fs::write(
+            "/tmp/tokens_dict_malformed_test.dict",
+            "no_quote_here\nkw=\"GET \" # trailing comment\n\"bare\"\n",
+        )
+        .unwrap();
+        let token_set = TokenSet::from_dict_file("/tmp/tokens_dict_malformed_test.dict");
+        std::fs::remove_file("/tmp/tokens_dict_malformed_test.dict").unwrap();
+
+        assert!(token_set.tokens_by_string.contains_key(b"GET ".as_slice()));
+        assert!(token_set.tokens_by_string.contains_key(b"bare".as_slice()));
+    }
+
+    #[test]
+    fn to_dict_file_round_trips_non_literal_tokens() {
+        let mut token_set = TokenSet::build_with_bin_literals();
+        token_set.add_token(b"GET ");
+        token_set.add_token(&[0x00, b'\\', b'"']);
+
+        token_set.to_dict_file("/tmp/tokens_dict_roundtrip.dict");
+        let loaded = TokenSet::from_dict_file("/tmp/tokens_dict_roundtrip.dict");
+        std::fs::remove_file("/tmp/tokens_dict_roundtrip.dict").unwrap();
+
+        assert!(loaded.tokens_by_string.contains_key(b"GET ".as_slice()));
+        assert!(loaded
+            .tokens_by_string
+            .contains_key([0x00, b'\\', b'"'].as_slice()));
+    }
+
+    #[test]
+    fn to_dict_file_excludes_mandatory_tokens() {
+        let token_set = TokenSet::build_with_bin_literals();
+        token_set.to_dict_file("/tmp/tokens_dict_mandatory_test.dict");
+        let contents = std::fs::read_to_string("/tmp/tokens_dict_mandatory_test.dict").unwrap();
+        std::fs::remove_file("/tmp/tokens_dict_mandatory_test.dict").unwrap();
+
+        assert_eq!(contents, "");
+    }
+
+    #[test]
+    fn train_bpe_converges_on_repeated_pattern() {
+        let mut token_set = TokenSet::build_with_bin_literals();
+        let corpus = b"abcabcabcabcabcabcabc";
+        let start_ntokens = token_set.ntokens();
+        token_set.train_bpe(corpus, start_ntokens + 1, 0);
+
+        assert_eq!(token_set.ntokens(), start_ntokens + 1);
+        assert!(
+            token_set.tokens_by_string.contains_key(b"ab".as_slice())
+                || token_set.tokens_by_string.contains_key(b"bc".as_slice())
+        );
+    }
+
+    #[test]
+    fn assign_costs_from_frequencies_penalizes_unseen_tokens() {
+        let mut token_set = TokenSet::build_with_bin_literals();
+        token_set.add_token(b"abc");
+        token_set.add_token(b"xyz");
+
+        let mut counts = HashMap::new();
+        counts.insert(b"abc".to_vec(), 10u64);
+        token_set.assign_costs_from_frequencies(&counts);
+
+        let abc_cost = token_set.tokens[token_set.tokens_by_string[b"abc".as_slice()]].cost;
+        let xyz_cost = token_set.tokens[token_set.tokens_by_string[b"xyz".as_slice()]].cost;
+        assert!(xyz_cost > abc_cost);
+    }
+
+    #[test]
+    fn token_cost_round_trips_through_json() {
+        let mut token_set = TokenSet::build_with_bin_literals();
+        token_set.add_token(b"abc");
+
+        let mut counts = HashMap::new();
+        counts.insert(b"abc".to_vec(), 3u64);
+        counts.insert(b"x".to_vec(), 1u64);
+        token_set.assign_costs_from_frequencies(&counts);
+
+        let expected_cost = token_set.tokens[token_set.tokens_by_string[b"abc".as_slice()]].cost;
+        let json_str = json::stringify(token_set.to_json());
+
+        std::fs::write("/tmp/tokens_roundtrip_test.json", &json_str).unwrap();
+        let loaded = TokenSet::from_json("/tmp/tokens_roundtrip_test.json");
+        std::fs::remove_file("/tmp/tokens_roundtrip_test.json").unwrap();
+
+        let loaded_cost = loaded.tokens[loaded.tokens_by_string[b"abc".as_slice()]].cost;
+        assert_eq!(loaded_cost, expected_cost);
+    }
+
+    #[test]
+    fn automaton_matches_every_token_ending_at_each_position() {
+        let mut token_set = TokenSet::build_with_bin_literals();
+        token_set.add_token(b"he");
+        token_set.add_token(b"she");
+        token_set.add_token(b"hers");
+        token_set.generate_suffixes();
+
+        let automaton = token_set.build_automaton();
+        let he = token_set.tokens_by_string[b"he".as_slice()];
+        let she = token_set.tokens_by_string[b"she".as_slice()];
+        let hers = token_set.tokens_by_string[b"hers".as_slice()];
+
+        let found: Vec<(usize, usize)> = automaton.matches(b"shers").collect();
+
+        assert!(found.contains(&(3, he)));
+        assert!(found.contains(&(3, she)));
+        assert!(found.contains(&(5, hers)));
+    }
+
+    #[test]
+    fn prune_to_reaches_target_size_without_panicking() {
+        let mut token_set = TokenSet::build_with_bin_literals();
+        token_set.add_token(b"ab");
+        token_set.add_token(b"abc");
+        token_set.add_token(b"abcd");
+        token_set.generate_suffixes();
+
+        let mut counts = HashMap::new();
+        counts.insert(b"ab".to_vec(), 5u64);
+        counts.insert(b"abc".to_vec(), 5u64);
+        counts.insert(b"abcd".to_vec(), 5u64);
+        token_set.assign_costs_from_frequencies(&counts);
+
+        let target = token_set.ntokens() - 2;
+        token_set.prune_to(target, b"abcdabcdabcd");
+
+        assert_eq!(token_set.ntokens(), target);
+    }
 }